@@ -0,0 +1,134 @@
+// Copyright (c) 2017 repomonc developers
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. All files in the project carrying such notice may not be copied,
+// modified, or distributed except according to those terms.
+
+//! Shared length-delimited `Message` framing, used by both the TCP and the
+//! Unix domain socket transports.
+use std::io;
+
+use bincode::{deserialize, serialize, Infinite};
+use bytes::BytesMut;
+use repomon::Message;
+use tokio_util::codec::{Decoder, Encoder};
+
+/// Number of bytes in the big-endian length header that precedes every frame.
+const HEADER_LEN: usize = 4;
+
+/// The largest payload (in bytes) we'll allocate for a single frame.
+///
+/// This guards against a malformed or malicious peer claiming an enormous
+/// frame length and driving us to allocate unbounded memory while we wait
+/// for a payload that will never arrive.
+const DEFAULT_MAX_FRAME_LEN: usize = 8 * 1024 * 1024;
+
+/// Read a big-endian `u32` out of the first 4 bytes of `buf`.
+fn read_u32_be(buf: &[u8]) -> u32 {
+    (u32::from(buf[0]) << 24) | (u32::from(buf[1]) << 16) | (u32::from(buf[2]) << 8)
+        | u32::from(buf[3])
+}
+
+/// Write `n` into `buf` as a big-endian `u32`.
+fn write_u32_be(buf: &mut [u8], n: u32) {
+    buf[0] = (n >> 24) as u8;
+    buf[1] = (n >> 16) as u8;
+    buf[2] = (n >> 8) as u8;
+    buf[3] = n as u8;
+}
+
+/// A length-delimited `Codec` implementation.
+///
+/// Frames are written as a 4-byte big-endian length header followed by that
+/// many bytes of bincode-serialized payload. This mirrors tokio's
+/// `length_delimited` codec and correctly handles a `Message` being split
+/// across multiple reads, as well as multiple `Message`s coalescing into a
+/// single read: `decode` only ever consumes one complete frame at a time,
+/// and the `Framed` transport calls it again immediately to drain any
+/// further frames already buffered.
+pub struct Bytes {
+    /// The largest frame payload (in bytes) this codec will accept. A peer
+    /// claiming a larger length causes `decode` to error out rather than
+    /// allocate unbounded memory.
+    max_frame_len: usize,
+}
+
+impl Default for Bytes {
+    fn default() -> Self {
+        Bytes {
+            max_frame_len: DEFAULT_MAX_FRAME_LEN,
+        }
+    }
+}
+
+impl Decoder for Bytes {
+    type Item = Message;
+    type Error = io::Error;
+
+    fn decode(&mut self, buf: &mut BytesMut) -> io::Result<Option<Message>> {
+        if buf.len() < HEADER_LEN {
+            return Ok(None);
+        }
+
+        let len = read_u32_be(&buf[..HEADER_LEN]) as usize;
+
+        if len > self.max_frame_len {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "frame of {} bytes exceeds the {} byte maximum",
+                    len, self.max_frame_len
+                ),
+            ));
+        }
+
+        if buf.len() < HEADER_LEN + len {
+            // The full frame hasn't arrived yet; wait for more bytes.
+            return Ok(None);
+        }
+
+        let _header = buf.split_to(HEADER_LEN);
+        let payload = buf.split_to(len);
+
+        match deserialize(payload.as_ref()) {
+            Ok(message) => Ok(Some(message)),
+            Err(e) => {
+                // The length header was read correctly but the payload
+                // didn't deserialize, so the stream itself is desynced
+                // (framing is byte-exact; there's no way to resync). Erroring
+                // here, rather than returning `Ok(None)`, matters: `Framed`
+                // treats `Ok(None)` as "wait for more bytes from the socket",
+                // which would stall any further frame already sitting in
+                // `buf` behind this one.
+                Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("failed to deserialize frame: {}", e),
+                ))
+            }
+        }
+    }
+
+    // fn decode_eof(&mut self, buf: &mut BytesMut) -> io::Result<Option<Message>> {
+    //     self.decode(buf)
+    // }
+}
+
+impl Encoder<Message> for Bytes {
+    type Error = io::Error;
+
+    fn encode(&mut self, data: Message, buf: &mut BytesMut) -> io::Result<()> {
+        match serialize(&data, Infinite) {
+            Ok(bytes) => {
+                let mut header = [0; HEADER_LEN];
+                write_u32_be(&mut header, bytes.len() as u32);
+                buf.reserve(HEADER_LEN + bytes.len());
+                buf.extend(header.iter());
+                buf.extend(bytes.iter());
+                Ok(())
+            }
+            Err(_e) => Ok(()),
+        }
+    }
+}
@@ -7,6 +7,8 @@
 // modified, or distributed except according to those terms.
 
 //! `repomonc` errors
+#![allow(unexpected_cfgs)] // `error_chain`'s expansion predates `unexpected_cfgs`
+
 error_chain!{
     foreign_links {
         AddrParse(::std::net::AddrParseError);
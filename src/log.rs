@@ -0,0 +1,35 @@
+// Copyright (c) 2017 repomonc developers
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. All files in the project carrying such notice may not be copied,
+// modified, or distributed except according to those terms.
+
+//! The loggers used by `repomonc`.
+use slog::{Drain, Level, Logger};
+use slog_async::Async;
+use slog_term::{FullFormat, TermDecorator};
+
+/// A handle to this process's loggers.
+///
+/// `stdout` is `None` until [`Logs::set_stdout_level`] has been called, so
+/// the `try_info!`/`try_warn!`/`try_error!`/`try_trace!` macros from
+/// `slog_try` silently no-op on an unconfigured `Logs` rather than panicking.
+#[derive(Clone, Default, Getters)]
+#[get = "pub"]
+pub struct Logs {
+    /// The logger that writes to stdout, once configured.
+    stdout: Option<Logger>,
+}
+
+impl Logs {
+    /// (Re)configure the stdout logger to emit at `level` and above.
+    pub fn set_stdout_level(&mut self, level: Level) {
+        let decorator = TermDecorator::new().build();
+        let drain = FullFormat::new(decorator).build().fuse();
+        let drain = drain.filter_level(level).fuse();
+        let drain = Async::new(drain).build().fuse();
+        self.stdout = Some(Logger::root(drain, o!()));
+    }
+}
@@ -6,13 +6,16 @@
 // option. All files in the project carrying such notice may not be copied,
 // modified, or distributed except according to those terms.
 
-//! An example of hooking up stdin/stdout to either a TCP or UDP stream.
+//! An example of hooking up stdin/stdout to a TCP, UDP, or Unix domain
+//! socket stream.
 //!
 //! This example will connect to a socket address specified in the argument list
 //! and then forward all data read on stdin to the server, printing out all data
 //! received on stdout. An optional `--udp` argument can be passed to specify
 //! that the connection should be made over UDP instead of TCP, translating each
-//! line entered on stdin to a UDP packet to be sent to the remote address.
+//! line entered on stdin to a UDP packet to be sent to the remote address. An
+//! optional `--unix <path>` argument connects over a Unix domain socket
+//! instead, for local-only deployments.
 //!
 //! Note that this is not currently optimized for performance, especially
 //! around buffer management. Rather it's intended to show an example of
@@ -36,24 +39,35 @@ extern crate bincode;
 extern crate bytes;
 extern crate clap;
 extern crate futures;
+extern crate rand;
 extern crate repomon;
 extern crate slog_async;
 extern crate slog_term;
-extern crate tokio_core;
-extern crate tokio_io;
+extern crate tokio;
+extern crate tokio_util;
 
 use std::io::{self, Write};
 use std::process;
 
+mod codec;
 mod error;
 mod log;
 mod run;
 mod tcp;
+mod uds;
 mod udp;
 
 /// CLI Entry Point
 fn main() {
-    match run::run() {
+    let runtime = match tokio::runtime::Runtime::new() {
+        Ok(runtime) => runtime,
+        Err(e) => {
+            writeln!(io::stderr(), "{}", e).expect("Unable to write to stderr!");
+            process::exit(1)
+        }
+    };
+
+    match runtime.block_on(run::run()) {
         Ok(i) => process::exit(i),
         Err(e) => {
             writeln!(io::stderr(), "{}", e).expect("Unable to write to stderr!");
@@ -7,29 +7,92 @@
 // modified, or distributed except according to those terms.
 
 //! `repomonc` runtime
+//!
+//! # Stdin control protocol
+//!
+//! Lines read from stdin are parsed as commands, one per line:
+//!
+//! ```text
+//! filter <info|ahead|behind|uptodate>
+//! ping
+//! ```
+//!
+//! `repomon::Message`, the server's notification type, exposes no public
+//! constructor beyond `Default` and no way to address a repository, so it
+//! can't be reused or extended here to carry client→server intent (it's
+//! also the wrong type for it — a notification isn't a command). `filter`
+//! is therefore handled entirely client-side: it replaces the live category
+//! filter shared with [`filter_messages`], taking effect immediately without
+//! reconnecting. `ping` is the one command actually forwarded to the server,
+//! as a keep-alive `Message::default()` — the only `Message` this crate can
+//! construct.
+//!
+//! Blank lines are skipped. Any other line that doesn't match this grammar
+//! is reported to stderr and otherwise ignored; it is never silently dropped.
+use crate::error::Result;
+use crate::log::Logs;
+use crate::tcp;
+use crate::uds;
+use crate::udp;
 use clap::{App, Arg};
-use error::Result;
-use futures::sync::mpsc;
-use futures::{Future, Sink, Stream};
-use log::Logs;
+use futures::{SinkExt, StreamExt};
+use rand::Rng;
 use repomon::{Category, Message};
 use slog::Level;
-use std::io::{self, Read};
+use std::io::{self, BufRead, Write};
 use std::net::SocketAddr;
-use std::thread;
-use tcp;
-use tokio_core::reactor::Core;
-use udp;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::mpsc;
 
 /// CLI Runtime
 #[allow(dead_code)]
-pub fn run() -> Result<i32> {
+pub async fn run() -> Result<i32> {
     let matches = App::new(env!("CARGO_PKG_NAME"))
         .version(env!("CARGO_PKG_VERSION"))
         .author(env!("CARGO_PKG_AUTHORS"))
         .about("Connects to a repomons server to receive notifications")
         .arg(Arg::with_name("udp").short("u").long("udp"))
+        .arg(
+            Arg::with_name("unix")
+                .long("unix")
+                .takes_value(true)
+                .value_name("PATH")
+                .conflicts_with("udp")
+                .help("Connect via a Unix domain socket at PATH instead of TCP/UDP"),
+        )
         .arg(Arg::with_name("address").default_value("127.0.0.1:8080"))
+        .arg(
+            Arg::with_name("max-retries")
+                .long("max-retries")
+                .takes_value(true)
+                .value_name("N")
+                .help("Give up after N reconnect attempts (default: retry forever)"),
+        )
+        .arg(
+            Arg::with_name("retry-cap")
+                .long("retry-cap")
+                .takes_value(true)
+                .value_name("MS")
+                .default_value("30000")
+                .help("Cap, in milliseconds, on the exponential reconnect backoff"),
+        )
+        .arg(
+            Arg::with_name("category")
+                .long("category")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .value_name("CATEGORY")
+                .possible_values(&["info", "ahead", "behind", "uptodate"])
+                .help("Only show messages in the given category (repeatable)"),
+        )
+        .arg(
+            Arg::with_name("changes-only")
+                .long("changes-only")
+                .help("Shortcut for suppressing uptodate messages"),
+        )
         .arg(
             Arg::with_name("verbose")
                 .short("v")
@@ -46,12 +109,6 @@ pub fn run() -> Result<i32> {
         )
         .get_matches();
 
-    // Parse what address we're going to connect to
-    let addr = matches
-        .value_of("address")
-        .ok_or("invalid address")?
-        .parse::<SocketAddr>()?;
-
     // Setup the logging (info by default)
     let mut level = match matches.occurrences_of("verbose") {
         0 => Level::Info,
@@ -69,63 +126,332 @@ pub fn run() -> Result<i32> {
     let mut logs: Logs = Default::default();
     logs.set_stdout_level(level);
 
-    // Create the event loop and initiate the connection to the remote server
-    let mut core = Core::new()?;
-    let handle = core.handle();
-
-    // Right now Tokio doesn't support a handle to stdin running on the event
-    // loop, so we farm out that work to a separate thread. This thread will
-    // read data (with blocking I/O) from stdin and then send it to the event
-    // loop over a standard futures channel.
-    let (stdin_tx, stdin_rx) = mpsc::channel(0);
-    thread::spawn(|| read_stdin(stdin_tx));
-    let stdin_rx = stdin_rx.map_err(|_| panic!()); // errors not possible on rx
-
-    // Now that we've got our stdin read we either set up our TCP connection or
-    // our UDP connection to get a stream of bytes we're going to emit to
-    // stdout.
-    let stdout = if matches.is_present("udp") {
-        udp::connect(&addr, &handle, Box::new(stdin_rx))
+    let max_retries = match matches.value_of("max-retries") {
+        Some(v) => Some(v.parse::<u32>().map_err(|_| "invalid --max-retries value")?),
+        None => None,
+    };
+    let retry_cap_ms = matches
+        .value_of("retry-cap")
+        .ok_or("invalid --retry-cap value")?
+        .parse::<u64>()
+        .map_err(|_| "invalid --retry-cap value")?;
+
+    // Which categories to show, if the user narrowed them down. `None` means
+    // "no filtering beyond --changes-only". Shared with the stdin control
+    // channel so a `filter <category>` command can replace it live.
+    let categories: Option<Vec<Category>> = matches.values_of("category").map(|values| {
+        values
+            .map(|v| parse_category(v).expect("validated by clap possible_values"))
+            .collect()
+    });
+    let changes_only = matches.is_present("changes-only");
+    let filter_state = Arc::new(Mutex::new(FilterState {
+        categories,
+        changes_only,
+    }));
+
+    // Resolve the transport once up front: a filesystem path selects the
+    // Unix domain socket backend, a `SocketAddr` selects TCP or UDP.
+    let target = if let Some(path) = matches.value_of("unix") {
+        Target::Unix(path.into())
     } else {
-        tcp::connect(&addr, &handle, &logs, Box::new(stdin_rx))
+        let addr = matches
+            .value_of("address")
+            .ok_or("invalid address")?
+            .parse::<SocketAddr>()?;
+
+        if matches.is_present("udp") {
+            Target::Udp(addr)
+        } else {
+            Target::Tcp(addr)
+        }
     };
 
-    // And now with our stream of bytes to write to stdout, we execute that in
-    // the event loop! Note that this is doing blocking I/O to emit data to
-    // stdout, and in general it's a no-no to do that sort of work on the event
-    // loop. In this case, though, we know it's ok as the event loop isn't
-    // otherwise running anything useful.
-    let stdout_clone = logs.stdout().clone();
-    core.run(stdout.for_each(|message| {
-        match *message.category() {
-            Category::Info | Category::Ahead | Category::Behind => {
-                try_info!(stdout_clone, "{}", &message);
-            }
-            Category::UpToDate => {
-                try_trace!(stdout_clone, "{}", &message);
+    // If the server restarts or the network drops, the connection's stream
+    // simply ends (or errors). Rather than letting that kill the process, we
+    // reconnect with an exponentially increasing, jittered delay, capped at
+    // `retry_cap_ms`, and only give up once `max_retries` attempts have been
+    // exhausted (by default we retry forever).
+    let mut attempt: u32 = 0;
+    let mut backoff_ms: u64 = 100;
+
+    // Stdin is read with blocking I/O, one line at a time, so we farm that
+    // out to the runtime's blocking thread pool rather than parking an
+    // executor thread on it. Unlike the transport connection, a
+    // `spawn_blocking` task can't be cancelled: dropping its `JoinHandle`
+    // doesn't stop it, it just stays parked in `stdin.lock().lines()`. So
+    // this reader is spawned once, for the life of the process, and its
+    // receiver is shared across reconnects, rather than respawning one per
+    // reconnect and leaking the old one to race the new one for input.
+    let (stdin_tx, mut stdin_rx) = mpsc::channel(16);
+    tokio::task::spawn_blocking(move || read_stdin(stdin_tx));
+    let mut stdin_closed = false;
+
+    loop {
+        let (mut sink, stream) = match connect(&target, &logs).await {
+            Ok(pair) => pair,
+            Err(e) => match backoff(&logs, &mut attempt, &mut backoff_ms, retry_cap_ms, max_retries, &e).await {
+                Ok(()) => continue,
+                Err(code) => return Ok(code),
+            },
+        };
+
+        // A connection just succeeded, so `attempt`/`backoff_ms` track
+        // *consecutive* failures from here, not failures accumulated over
+        // the whole process lifetime; otherwise a long-lived client that
+        // reconnects occasionally would eventually exhaust `--max-retries`
+        // and exit even though every individual reconnect succeeded.
+        attempt = 0;
+        backoff_ms = 100;
+
+        let mut stream = filter_messages(stream, Arc::clone(&filter_state));
+        let mut last_error = None;
+
+        // Apply stdin commands concurrently with reading messages from the
+        // stream, so neither side has to wait on the other. `stdin_closed`
+        // latches once `stdin_rx` is drained so the branch doesn't spin
+        // after EOF.
+        loop {
+            tokio::select! {
+                maybe_command = stdin_rx.recv(), if !stdin_closed => {
+                    match maybe_command {
+                        Some(Command::Filter(category)) => {
+                            filter_state
+                                .lock()
+                                .expect("filter state mutex poisoned")
+                                .categories = Some(vec![category]);
+                        }
+                        Some(Command::Ping) => {
+                            if let Err(e) = sink.send(Message::default()).await {
+                                last_error = Some(e);
+                                break;
+                            }
+                        }
+                        None => stdin_closed = true,
+                    }
+                }
+                result = stream.next() => {
+                    match result {
+                        Some(Ok(message)) => match *message.category() {
+                            Category::Info | Category::Ahead | Category::Behind => {
+                                try_info!(logs.stdout(), "{}", &message);
+                            }
+                            Category::UpToDate => {
+                                try_trace!(logs.stdout(), "{}", &message);
+                            }
+                        },
+                        Some(Err(e)) => {
+                            last_error = Some(e);
+                            break;
+                        }
+                        None => break,
+                    }
+                }
             }
         }
 
-        Ok(())
-    }))?;
+        // A stream that ends without an error means the server closed the
+        // connection, which is retried like any other failure.
+        let e = last_error.unwrap_or_else(|| {
+            io::Error::new(io::ErrorKind::UnexpectedEof, "connection closed by server")
+        });
 
-    Ok(0)
+        match backoff(&logs, &mut attempt, &mut backoff_ms, retry_cap_ms, max_retries, &e).await {
+            Ok(()) => continue,
+            Err(code) => return Ok(code),
+        }
+    }
 }
 
-/// Our helper method which will read data from stdin and send it along the
-/// sender provided.
-fn read_stdin(mut tx: mpsc::Sender<Message>) {
-    let mut stdin = io::stdin();
-    loop {
-        let mut buf = vec![0; 1024];
-        let n = match stdin.read(&mut buf) {
-            Err(_) | Ok(0) => break,
-            Ok(n) => n,
+/// The transport to connect over, resolved once from the CLI arguments.
+enum Target {
+    /// Connect over TCP to this address.
+    Tcp(SocketAddr),
+    /// Connect over UDP to this address.
+    Udp(SocketAddr),
+    /// Connect over a Unix domain socket at this path.
+    Unix(PathBuf),
+}
+
+/// Establish a single connection attempt over the resolved `Target`.
+async fn connect(
+    target: &Target,
+    logs: &Logs,
+) -> io::Result<(
+    Box<dyn futures::Sink<Message, Error = io::Error> + Unpin + Send>,
+    Box<dyn futures::Stream<Item = io::Result<Message>> + Unpin + Send>,
+)> {
+    match *target {
+        Target::Tcp(ref addr) => tcp::connect(addr, logs).await,
+        Target::Udp(ref addr) => udp::connect(addr).await,
+        Target::Unix(ref path) => uds::connect(path).await,
+    }
+}
+
+/// The live `--category`/`--changes-only` filter, shared between the stdin
+/// control channel and [`filter_messages`] so a `filter <category>` command
+/// can replace it without reconnecting.
+#[derive(Default)]
+struct FilterState {
+    /// Show only these categories, or all of them if `None`.
+    categories: Option<Vec<Category>>,
+    /// Suppress `Category::UpToDate` regardless of `categories`.
+    changes_only: bool,
+}
+
+/// Wrap `stream` so that messages excluded by `filter_state` never reach
+/// the consumer in `run`, rather than being filtered out in the consumer
+/// loop itself. This is inserted once, right after `connect`, so the
+/// filtering logic is identical no matter which backend (TCP/UDP/UDS)
+/// produced the stream, and it re-reads `filter_state` on every message so a
+/// `filter` command issued over stdin takes effect immediately. Transport
+/// errors always pass through unfiltered so the reconnect logic still sees
+/// them.
+fn filter_messages(
+    stream: Box<dyn futures::Stream<Item = io::Result<Message>> + Unpin + Send>,
+    filter_state: Arc<Mutex<FilterState>>,
+) -> Box<dyn futures::Stream<Item = io::Result<Message>> + Unpin + Send> {
+    Box::new(stream.filter(move |item| {
+        let keep = match *item {
+            Ok(ref message) => {
+                let category = message.category();
+                let state = filter_state.lock().expect("filter state mutex poisoned");
+
+                if state.changes_only && *category == Category::UpToDate {
+                    false
+                } else {
+                    state
+                        .categories
+                        .as_ref()
+                        .is_none_or(|categories| categories.iter().any(|c| c == category))
+                }
+            }
+            Err(_) => true,
         };
-        buf.truncate(n);
-        tx = match tx.send(Default::default()).wait() {
-            Ok(tx) => tx,
+
+        futures::future::ready(keep)
+    }))
+}
+
+/// Wait for the given number of milliseconds, applying exponential backoff
+/// and jitter, before the next reconnect attempt. Returns `Err` with the
+/// process exit code once `max_retries` attempts have been exhausted.
+async fn backoff(
+    logs: &Logs,
+    attempt: &mut u32,
+    backoff_ms: &mut u64,
+    retry_cap_ms: u64,
+    max_retries: Option<u32>,
+    error: &io::Error,
+) -> ::std::result::Result<(), i32> {
+    *attempt += 1;
+
+    if let Some(max) = max_retries {
+        if *attempt > max {
+            try_error!(
+                logs.stdout(),
+                "giving up after {} reconnect attempt(s): {}",
+                *attempt - 1,
+                error
+            );
+            return Err(1);
+        }
+    }
+
+    // Jitter up to 10% of the current backoff, drawn fresh from the
+    // thread-local RNG on every attempt, so a fleet of clients
+    // reconnecting at once doesn't hammer the server in lockstep.
+    let jitter = rand::thread_rng().gen_range(0..*backoff_ms / 10 + 1);
+    let sleep_ms = *backoff_ms + jitter;
+
+    try_warn!(
+        logs.stdout(),
+        "connection lost ({}), reconnecting in {}ms (attempt {})",
+        error,
+        sleep_ms,
+        *attempt
+    );
+
+    tokio::time::sleep(Duration::from_millis(sleep_ms)).await;
+    *backoff_ms = (*backoff_ms * 2).min(retry_cap_ms);
+
+    Ok(())
+}
+
+/// A command parsed from a line of stdin. See the module docs for the
+/// accepted grammar.
+///
+/// This is local to `repomonc`, not `repomon::Message`: the latter is the
+/// server's notification type, has no constructor for addressing a
+/// repository, and isn't the right shape for client→server intent anyway.
+/// `Command` is interpreted entirely in `run` (see there for how each
+/// variant is applied).
+enum Command {
+    /// Replace the live category filter.
+    Filter(Category),
+    /// A keep-alive, forwarded to the server as `Message::default()`.
+    Ping,
+}
+
+/// Our helper method which will read commands from stdin, one per line, and
+/// send the parsed `Command` along the sender provided. See the module docs
+/// for the accepted grammar.
+///
+/// This runs on the runtime's blocking thread pool (see `spawn_blocking` in
+/// `run`), since it does blocking I/O against stdin.
+fn read_stdin(tx: mpsc::Sender<Command>) {
+    let stdin = io::stdin();
+    for line in stdin.lock().lines() {
+        let line = match line {
+            Ok(line) => line,
             Err(_) => break,
         };
+
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let command = match parse_command(&line) {
+            Ok(command) => command,
+            Err(e) => {
+                let _ = writeln!(io::stderr(), "malformed command '{}': {}", line, e);
+                continue;
+            }
+        };
+
+        if tx.blocking_send(command).is_err() {
+            break;
+        }
+    }
+}
+
+/// Parse a single line of stdin input into a `Command`.
+fn parse_command(line: &str) -> ::std::result::Result<Command, String> {
+    let mut parts = line.trim().splitn(2, ' ');
+
+    match parts.next() {
+        Some("filter") => parts
+            .next()
+            .ok_or_else(|| "filter requires a <category>".to_string())
+            .and_then(parse_category)
+            .map(Command::Filter),
+        Some("ping") => match parts.next() {
+            None => Ok(Command::Ping),
+            Some(_) => Err("ping takes no arguments".to_string()),
+        },
+        Some(cmd) => Err(format!("unrecognized command '{}'", cmd)),
+        None => Err("empty command".to_string()),
+    }
+}
+
+/// Parse a category name as used on the stdin control channel and in
+/// `--category`.
+fn parse_category(s: &str) -> ::std::result::Result<Category, String> {
+    match s {
+        "info" => Ok(Category::Info),
+        "ahead" => Ok(Category::Ahead),
+        "behind" => Ok(Category::Behind),
+        "uptodate" => Ok(Category::UpToDate),
+        _ => Err(format!("unrecognized category '{}'", s)),
     }
 }
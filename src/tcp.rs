@@ -6,102 +6,31 @@
 // option. All files in the project carrying such notice may not be copied,
 // modified, or distributed except according to those terms.
 
-//! TCP future stream handling.
+//! TCP stream handling.
 use std::io;
 use std::net::SocketAddr;
 
-use bincode::{deserialize, serialize, Infinite};
-use bytes::BytesMut;
-use futures::{Future, Stream};
+use crate::codec::Bytes;
+use crate::log::Logs;
+use futures::{Sink, Stream, StreamExt};
 use repomon::Message;
-use tokio_core::net::TcpStream;
-use tokio_core::reactor::Handle;
-use tokio_io::AsyncRead;
-use tokio_io::codec::{Decoder, Encoder};
+use tokio::net::TcpStream;
+use tokio_util::codec::Framed;
 
 /// Connect to the given address via a `TcpStream`.
-pub fn connect(
-    addr: &SocketAddr,
-    handle: &Handle,
-    stdin: Box<Stream<Item = Message, Error = io::Error>>,
-) -> Box<Stream<Item = Message, Error = io::Error>> {
-    let tcp = TcpStream::connect(addr, handle);
-    let handle = handle.clone();
-
-    // After the TCP connection has been established, we set up our client
-    // to start forwarding data.
-    //
-    // First we use the `Io::framed` method with a simple implementation of
-    // a `Codec` (listed below) that just ships bytes around. We then split
-    // that in two to work with the stream and sink separately.
-    //
-    // Half of the work we're going to do is to take all data we receive on
-    // `stdin` and send that along the TCP stream (`sink`). The second half
-    // is to take all the data we receive (`stream`) and then write that to
-    // stdout. We'll be passing this handle back out from this method.
-    //
-    // You'll also note that we *spawn* the work to read stdin and write it
-    // to the TCP stream. This is done to ensure that happens concurrently
-    // with us reading data from the stream.
-    Box::new(tcp.map(move |stream| {
-        let (sink, stream) = stream.framed(Bytes).split();
-        handle.spawn(stdin.forward(sink).then(|result| {
-            if let Err(e) = result {
-                panic!("failed to write to socket: {}", e)
-            }
-            Ok(())
-        }));
-        stream
-    }).flatten_stream())
-}
-
-/// A simple `Codec` implementation that just ships bytes around.
 ///
-/// This type is used for "framing" a TCP stream of bytes but it's really
-/// just a convenient method for us to work with streams/sinks for now.
-/// This'll just take any data read and interpret it as a "frame" and
-/// conversely just shove data into the output location without looking at
-/// it.
-struct Bytes;
-
-impl Decoder for Bytes {
-    type Item = Message;
-    type Error = io::Error;
-
-    fn decode(&mut self, buf: &mut BytesMut) -> io::Result<Option<Message>> {
-        use std::io::{self, Write};
-        if buf.is_empty() {
-            Ok(None)
-        } else {
-            let len = buf.len();
-            let bytes = buf.split_to(len);
-
-            match deserialize(bytes.as_ref()) {
-                Ok(message) => Ok(Some(message)),
-                Err(e) => {
-                    writeln!(io::stderr(), "{}", e)?;
-                    Ok(None)
-                }
-            }
-        }
-    }
-
-    // fn decode_eof(&mut self, buf: &mut BytesMut) -> io::Result<Option<Message>> {
-    //     self.decode(buf)
-    // }
-}
-
-impl Encoder for Bytes {
-    type Item = Message;
-    type Error = io::Error;
-
-    fn encode(&mut self, data: Message, buf: &mut BytesMut) -> io::Result<()> {
-        match serialize(&data, Infinite) {
-            Ok(bytes) => {
-                buf.extend(bytes.iter());
-                Ok(())
-            }
-            Err(_e) => Ok(()),
-        }
-    }
+/// Returns the sink/stream halves of a `Framed` transport: the sink accepts
+/// `Message`s to write to the socket, and the stream yields `Message`s (or
+/// I/O errors) read from it. The caller is responsible for driving both.
+pub async fn connect(
+    addr: &SocketAddr,
+    logs: &Logs,
+) -> io::Result<(
+    Box<dyn Sink<Message, Error = io::Error> + Unpin + Send>,
+    Box<dyn Stream<Item = io::Result<Message>> + Unpin + Send>,
+)> {
+    let stream = TcpStream::connect(addr).await?;
+    try_info!(logs.stdout(), "connected to {} over tcp", addr);
+    let (sink, stream) = Framed::new(stream, Bytes::default()).split();
+    Ok((Box::new(sink), Box::new(stream)))
 }
@@ -6,85 +6,56 @@
 // option. All files in the project carrying such notice may not be copied,
 // modified, or distributed except according to those terms.
 
-//! UDP future stream handling.
+//! UDP stream handling.
 use std::io;
 use std::net::SocketAddr;
 
-use bincode::{deserialize, serialize, Infinite};
-use futures::{Future, Stream};
+use crate::codec::Bytes;
+use futures::{Sink, SinkExt, Stream, StreamExt};
 use repomon::Message;
-use tokio_core::net::{UdpCodec, UdpSocket};
-use tokio_core::reactor::Handle;
-
-/// Connect the the given address via a `UdpSocket`.
-pub fn connect(
-    &addr: &SocketAddr,
-    handle: &Handle,
-    stdin: Box<dyn Stream<Item = Message, Error = io::Error>>,
-) -> Box<dyn Stream<Item = Message, Error = io::Error>> {
+use tokio::net::UdpSocket;
+use tokio_util::udp::UdpFramed;
+
+/// Connect to the given address via a `UdpSocket`.
+///
+/// Returns the sink/stream halves of a `UdpFramed` transport, adapted down
+/// to plain `Message`s: the sink addresses every outgoing `Message` to
+/// `addr`, and the stream discards anything received from a different
+/// address (UDP has no notion of a "connected" peer, so we could otherwise
+/// receive datagrams from anywhere).
+pub async fn connect(
+    addr: &SocketAddr,
+) -> io::Result<(
+    Box<dyn Sink<Message, Error = io::Error> + Unpin + Send>,
+    Box<dyn Stream<Item = io::Result<Message>> + Unpin + Send>,
+)> {
     // We'll bind our UDP socket to a local IP/port, but for now we
     // basically let the OS pick both of those.
-    let addr_to_bind = if addr.ip().is_ipv4() {
+    let addr_to_bind: SocketAddr = if addr.ip().is_ipv4() {
         "0.0.0.0:0".parse().expect("failed to parse ipv4 address")
     } else {
         "[::]:0".parse().expect("failed to parse ipv6 address")
     };
-    let udp = UdpSocket::bind(&addr_to_bind, handle).expect("failed to bind socket");
+    let socket = UdpSocket::bind(addr_to_bind).await?;
 
-    // Like above with TCP we use an instance of `UdpCodec` to transform
-    // this UDP socket into a framed sink/stream which operates over
-    // discrete values. In this case we're working with *pairs* of socket
-    // addresses and byte buffers.
-    let (sink, stream) = udp.framed(Bytes).split();
+    let framed = UdpFramed::new(socket, Bytes::default());
+    let (sink, stream) = framed.split();
 
-    // All bytes from `stdin` will go to the `addr` specified in our
-    // argument list. Like with TCP this is spawned concurrently
-    handle.spawn(
-        stdin
-            .map(move |chunk| (addr, chunk))
-            .forward(sink)
-            .then(|result| {
-                if let Err(e) = result {
-                    panic!("failed to write to socket: {}", e)
-                }
-                Ok(())
-            }),
-    );
+    let addr = *addr;
+    let sink = sink.with(move |message| futures::future::ok::<_, io::Error>((message, addr)));
 
-    // With UDP we could receive data from any source, so filter out
-    // anything coming from a different address
-    Box::new(stream.filter_map(
-        move |(src, chunk)| {
-            if src == addr {
-                Some(chunk)
-            } else {
-                None
+    let stream = stream.filter_map(move |result| {
+        futures::future::ready(match result {
+            Ok((message, src)) => {
+                if src == addr {
+                    Some(Ok(message))
+                } else {
+                    None
+                }
             }
-        },
-    ))
-}
+            Err(e) => Some(Err(e)),
+        })
+    });
 
-/// Bytes Unit Struct
-struct Bytes;
-
-impl UdpCodec for Bytes {
-    type In = (SocketAddr, Message);
-    type Out = (SocketAddr, Message);
-
-    fn decode(&mut self, addr: &SocketAddr, buf: &[u8]) -> io::Result<Self::In> {
-        match deserialize(buf) {
-            Ok(message) => Ok((*addr, message)),
-            Err(_) => Ok((*addr, Default::default())),
-        }
-    }
-
-    fn encode(&mut self, (addr, message): Self::Out, into: &mut Vec<u8>) -> SocketAddr {
-        match serialize(&message, Infinite) {
-            Ok(bytes) => {
-                into.extend(bytes.iter());
-            }
-            Err(_e) => {}
-        }
-        addr
-    }
+    Ok((Box::new(sink), Box::new(stream)))
 }
@@ -0,0 +1,33 @@
+// Copyright (c) 2017 repomonc developers
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. All files in the project carrying such notice may not be copied,
+// modified, or distributed except according to those terms.
+
+//! Unix domain socket stream handling.
+use std::io;
+use std::path::Path;
+
+use crate::codec::Bytes;
+use futures::{Sink, Stream, StreamExt};
+use repomon::Message;
+use tokio::net::UnixStream;
+use tokio_util::codec::Framed;
+
+/// Connect to the given path via a `UnixStream`.
+///
+/// This mirrors `tcp::connect`: the stream is framed with the same
+/// length-delimited `Bytes` codec and split into a sink/stream pair for the
+/// caller to drive.
+pub async fn connect(
+    path: &Path,
+) -> io::Result<(
+    Box<dyn Sink<Message, Error = io::Error> + Unpin + Send>,
+    Box<dyn Stream<Item = io::Result<Message>> + Unpin + Send>,
+)> {
+    let stream = UnixStream::connect(path).await?;
+    let (sink, stream) = Framed::new(stream, Bytes::default()).split();
+    Ok((Box::new(sink), Box::new(stream)))
+}